@@ -0,0 +1,95 @@
+use anyhow::{anyhow, bail};
+use indexmap::IndexMap;
+
+/// A frontmatter block still in its original, serialized form, tagged with
+/// the format its fence indicates.
+#[derive(Debug)]
+pub enum RawFrontMatter<'a> {
+    Toml(&'a str),
+    Yaml(&'a str),
+}
+
+impl RawFrontMatter<'_> {
+    /// Deserializes the frontmatter into the value representation the rest
+    /// of the tool works with, regardless of its original format.
+    pub fn deserialize(&self) -> anyhow::Result<IndexMap<String, toml::Value>> {
+        match self {
+            RawFrontMatter::Toml(s) => Ok(toml::from_str(s)?),
+            RawFrontMatter::Yaml(s) => Ok(serde_yaml::from_str(s)?),
+        }
+    }
+}
+
+/// Splits `content` into its frontmatter block and the remaining body.
+///
+/// The frontmatter format is detected from its fence: `---` opens and closes
+/// a YAML block, `+++` opens and closes a TOML block.
+pub fn split(content: &str) -> anyhow::Result<(RawFrontMatter<'_>, &str)> {
+    let fence = if content.starts_with("---") {
+        "---"
+    } else if content.starts_with("+++") {
+        "+++"
+    } else {
+        bail!("file must start with a `---` or `+++` frontmatter fence");
+    };
+
+    let after_open = content
+        .strip_prefix(fence)
+        .and_then(|s| s.strip_prefix('\n'))
+        .ok_or_else(|| anyhow!("expected newline after opening `{fence}` fence"))?;
+
+    let close_marker = format!("\n{fence}\n");
+    let (frontmatter, body) = match after_open.find(&close_marker) {
+        Some(idx) => (&after_open[..idx], &after_open[idx + close_marker.len()..]),
+        // tolerate a closing fence with no trailing body or newline
+        None if after_open.trim_end_matches('\n') == fence
+            || after_open.ends_with(&format!("\n{fence}")) =>
+        {
+            let idx = after_open.rfind(fence).unwrap();
+            (after_open[..idx].trim_end_matches('\n'), "")
+        }
+        None => bail!("couldn't find end of frontmatter"),
+    };
+
+    let raw = match fence {
+        "---" => RawFrontMatter::Yaml(frontmatter),
+        _ => RawFrontMatter::Toml(frontmatter),
+    };
+
+    Ok((raw, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, RawFrontMatter};
+
+    #[test]
+    fn splits_yaml_fence() {
+        let (raw, body) = split("---\ntitle: hello\n---\nbody text\n").unwrap();
+        assert!(matches!(raw, RawFrontMatter::Yaml("title: hello")));
+        assert_eq!(body, "body text\n");
+    }
+
+    #[test]
+    fn splits_toml_fence() {
+        let (raw, body) = split("+++\ntitle = \"hello\"\n+++\nbody text\n").unwrap();
+        assert!(matches!(raw, RawFrontMatter::Toml("title = \"hello\"")));
+        assert_eq!(body, "body text\n");
+    }
+
+    #[test]
+    fn tolerates_missing_trailing_newline() {
+        let (raw, body) = split("+++\ntitle = \"hello\"\n+++").unwrap();
+        assert!(matches!(raw, RawFrontMatter::Toml("title = \"hello\"")));
+        assert_eq!(body, "");
+    }
+
+    #[test]
+    fn rejects_content_without_a_fence() {
+        let err = split("title: hello\nbody text\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "file must start with a `---` or `+++` frontmatter fence"
+        );
+    }
+}
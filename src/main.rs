@@ -1,36 +1,114 @@
 use std::{
     env,
     fs::{self, File},
-    io::{BufRead, BufReader, BufWriter, Write},
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
     process,
 };
 
-use anyhow::bail;
+use anyhow::{bail, Context};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use git2::{Delta, DiffFindOptions, DiffOptions, Repository, Sort};
 use heck::ToKebabCase;
 use indexmap::IndexMap;
-use itertools::Itertools;
 use toml::value::Table;
-use xshell::{cmd, pushd};
+
+mod front_matter;
 
 fn main() -> anyhow::Result<()> {
     let args: Vec<_> = env::args().skip(1).collect();
-    let input_path = match args.as_slice() {
-        [input] => Path::new(input),
-        _ => {
-            eprintln!("must receive exactly one command line argument (input file)");
-            process::exit(1);
+
+    let mut input = None;
+    let mut output_dir = None;
+    let mut args_iter = args.into_iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--output-dir" => {
+                let dir = args_iter
+                    .next()
+                    .unwrap_or_else(|| usage("--output-dir requires a value"));
+                output_dir = Some(PathBuf::from(dir));
+            }
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => usage("unexpected extra argument"),
         }
-    };
+    }
+
+    let input_path = input.unwrap_or_else(|| usage("must receive an input file or directory"));
+    let output_dir = output_dir.unwrap_or_else(|| PathBuf::from("."));
+
+    if input_path.is_dir() {
+        convert_tree(&input_path, &output_dir)
+    } else {
+        convert_file(&input_path, &output_dir)
+    }
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{message}");
+    eprintln!("usage: matrix-blog-migrate [--output-dir <dir>] <input file or directory>");
+    process::exit(1);
+}
+
+/// Converts every `*.mdx` file found under `input_dir`. A failing file is
+/// recorded in `failures` and does not stop the rest of the tree from being
+/// converted; all failures are reported together once the walk is done.
+fn convert_tree(input_dir: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut num_converted = 0;
+    let mut failures = Vec::new();
+
+    for input_path in find_mdx_files(input_dir)? {
+        match convert_file(&input_path, output_dir) {
+            Ok(()) => num_converted += 1,
+            Err(e) => failures.push((input_path, e)),
+        }
+    }
+
+    println!("converted {num_converted} file(s)");
+    if !failures.is_empty() {
+        eprintln!("failed to convert {} file(s):", failures.len());
+        for (path, error) in &failures {
+            eprintln!("  {}: {error}", path.display());
+        }
+        process::exit(1);
+    }
+
+    Ok(())
+}
 
-    assert!(input_path.ends_with(".mdx"));
+fn find_mdx_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut mdx_files = Vec::new();
+    let mut dirs_to_visit = vec![dir.to_owned()];
+
+    while let Some(dir) = dirs_to_visit.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                dirs_to_visit.push(path);
+            } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == "mdx") {
+                mdx_files.push(path);
+            }
+        }
+    }
+
+    Ok(mdx_files)
+}
+
+fn convert_file(input_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    if input_path.extension().is_none_or(|ext| ext != "mdx") {
+        bail!("{} does not have a `.mdx` extension", input_path.display());
+    }
 
     let (date, mut updated) = git_timestamps(input_path)?;
     let mut date = Some(date);
-    let (yaml_frontmatter, markdown) = read_file_contents(input_path)?;
 
-    let mut frontmatter_value: IndexMap<String, toml::Value> =
-        serde_yaml::from_str(&yaml_frontmatter)?;
+    let content = fs::read_to_string(input_path)
+        .with_context(|| format!("reading {}", input_path.display()))?;
+    let (raw_frontmatter, markdown) = front_matter::split(&content)?;
+    let mut frontmatter_value = raw_frontmatter.deserialize()?;
 
     let frontmatter_date = match frontmatter_value
         .shift_remove("date")
@@ -42,7 +120,15 @@ fn main() -> anyhow::Result<()> {
 
     {
         let date_str = date.as_ref().unwrap();
-        if !date_str.starts_with(&frontmatter_date) {
+        // compare in the commit's own recorded offset, not UTC: converting
+        // first could shift a commit made near local midnight onto the
+        // neighboring day and produce a false mismatch
+        let commit_date = DateTime::parse_from_rfc3339(date_str)
+            .with_context(|| format!("parsing git commit date `{date_str}`"))?;
+        let frontmatter_day = NaiveDate::parse_from_str(&frontmatter_date, "%Y-%m-%d")
+            .with_context(|| format!("parsing frontmatter date `{frontmatter_date}`"))?;
+
+        if commit_date.date_naive() != frontmatter_day {
             eprintln!(
             "warning: date mismatch, git date = {date_str}, frontmatter date = {frontmatter_date}"
         );
@@ -79,11 +165,11 @@ fn main() -> anyhow::Result<()> {
     let day = &frontmatter_date[8..];
 
     if let Some(ts) = date {
-        frontmatter_value.insert("date".to_owned(), utc_iso_date(ts).into());
+        frontmatter_value.insert("date".to_owned(), to_toml_datetime(&ts)?.into());
     }
 
     if let Some(ts) = updated {
-        frontmatter_value.insert("updated".to_owned(), utc_iso_date(ts).into());
+        frontmatter_value.insert("updated".to_owned(), to_toml_datetime(&ts)?.into());
     }
 
     frontmatter_value.insert(
@@ -97,7 +183,8 @@ fn main() -> anyhow::Result<()> {
 
     let toml_frontmatter = toml::to_string(&frontmatter_value)?;
 
-    let output_path = PathBuf::from(format!("{year}/{month}/{year}-{month}-{day}-{slug}.md"));
+    let output_path =
+        output_dir.join(format!("{year}/{month}/{year}-{month}-{day}-{slug}.md"));
     fs::create_dir_all(output_path.parent().unwrap())?;
 
     let mut writer = BufWriter::new(File::create(output_path)?);
@@ -106,47 +193,97 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Walks the commit history of `input_path`, following renames, and gathers
+/// the timestamp of every commit that touched the file under its name at
+/// the time. `select_timestamps` then picks the creation and last-update
+/// dates out of the result.
 fn git_timestamps(input_path: &Path) -> anyhow::Result<(String, Option<String>)> {
-    let _guard = pushd(input_path.parent().expect("input file path has parent"));
-    let input_file_name = input_path
-        .file_name()
-        .expect("input file path has file name");
-    let git_file_timestamps =
-        cmd!("git log --format=%cd --date=iso-strict -- {input_file_name}").read()?;
-    let mut git_file_timestamps = git_file_timestamps.lines();
-    let date = git_file_timestamps
-        .next()
-        .expect("git log command returned at least one line");
-    let updated = git_file_timestamps.next_back();
-
-    Ok((date.to_owned(), updated.map(ToOwned::to_owned)))
-}
+    let input_path = input_path
+        .canonicalize()
+        .with_context(|| format!("resolving {}", input_path.display()))?;
+    let repo = Repository::discover(&input_path)
+        .with_context(|| format!("opening git repository for {}", input_path.display()))?;
+    let workdir = repo
+        .workdir()
+        .context("repository has no working directory")?
+        .canonicalize()?;
 
-fn read_file_contents(input_path: &Path) -> anyhow::Result<(String, String)> {
-    let input = BufReader::new(File::open(input_path)?);
-    let mut input_lines = input.lines();
+    // the path the file is known by in the commit currently being examined;
+    // walking back through a rename updates this to the file's old name
+    let mut path_of_interest = input_path
+        .strip_prefix(&workdir)
+        .context("input file is not inside its repository")?
+        .to_owned();
 
-    let first_line = input_lines.next().expect("input file is non-empty")?;
-    assert_eq!(first_line, "---", "File must start with YAML frontmatter");
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(Sort::TIME)?;
 
-    let mut frontmatter = String::new();
-    loop {
-        match input_lines.next().transpose()? {
-            Some(s) if s == "---" => break,
-            Some(s) => {
-                frontmatter += s.as_str();
-                frontmatter.push('\n');
-            }
-            None => bail!("Couldn't find end of frontmatter"),
+    let mut timestamps = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+
+        let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut DiffOptions::new()))?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+        let Some(delta) = diff
+            .deltas()
+            .find(|delta| delta.new_file().path() == Some(path_of_interest.as_path()))
+        else {
+            continue;
+        };
+
+        timestamps.push(format_git_time(commit.time()));
+
+        // the file was added in this commit; there's no earlier history to follow
+        if delta.status() == Delta::Added {
+            break;
+        }
+        if let Some(old_path) = delta.old_file().path() {
+            path_of_interest = old_path.to_owned();
         }
     }
 
-    let markdown = input_lines
-        // Okay for I/O errors to panic in this simple script
-        .map(|result| result.unwrap())
-        .join("\n");
+    select_timestamps(timestamps)
+}
+
+/// Picks the earliest and latest timestamp out of `timestamps` without
+/// assuming they're already sorted. Returns `None` for the latest timestamp
+/// when there's only one commit.
+fn select_timestamps(timestamps: Vec<String>) -> anyhow::Result<(String, Option<String>)> {
+    let mut timestamps = timestamps
+        .into_iter()
+        .map(|s| {
+            let utc = DateTime::parse_from_rfc3339(&s)
+                .with_context(|| format!("parsing commit timestamp `{s}`"))?
+                .with_timezone(&Utc);
+            Ok((utc, s))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    timestamps.sort_by_key(|(utc, _)| *utc);
+
+    let date = timestamps
+        .first()
+        .context("no commit touches the file")?
+        .1
+        .clone();
+    let updated = (timestamps.len() > 1).then(|| timestamps.last().unwrap().1.clone());
 
-    Ok((frontmatter, markdown))
+    Ok((date, updated))
+}
+
+fn format_git_time(time: git2::Time) -> String {
+    let offset = FixedOffset::east_opt(time.offset_minutes() * 60)
+        .expect("git commit offset is a valid UTC offset");
+    DateTime::from_timestamp(time.seconds(), 0)
+        .expect("git commit timestamp is a valid unix timestamp")
+        .with_timezone(&offset)
+        .to_rfc3339()
 }
 
 fn convert_taxonomy(
@@ -173,8 +310,45 @@ fn convert_taxonomy(
     Ok(())
 }
 
-fn utc_iso_date(iso_date: String) -> String {
-    cmd!("date --date={iso_date} +%Y-%m-%dT%H:%M:%SZ")
-        .read()
-        .expect("date conversion works")
+/// Parses an RFC 3339 timestamp and re-emits it as a UTC `toml::value::Datetime`.
+fn to_toml_datetime(iso_date: &str) -> anyhow::Result<toml::value::Datetime> {
+    let utc_date = DateTime::parse_from_rfc3339(iso_date)
+        .with_context(|| format!("parsing git commit date `{iso_date}`"))?
+        .with_timezone(&Utc);
+
+    utc_date
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+        .parse()
+        .context("formatting TOML datetime")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_timestamps;
+
+    #[test]
+    fn picks_earliest_and_latest_out_of_order_timestamps() {
+        let timestamps = vec![
+            "2021-06-01T12:00:00Z".to_owned(),
+            "2019-03-15T08:30:00Z".to_owned(),
+            "2023-01-10T00:00:00Z".to_owned(),
+            "2020-11-20T23:59:59Z".to_owned(),
+        ];
+
+        let (date, updated) = select_timestamps(timestamps).unwrap();
+
+        assert_eq!(date, "2019-03-15T08:30:00Z");
+        assert_eq!(updated.as_deref(), Some("2023-01-10T00:00:00Z"));
+    }
+
+    #[test]
+    fn single_commit_has_no_updated_timestamp() {
+        let timestamps = vec!["2022-07-04T00:00:00Z".to_owned()];
+
+        let (date, updated) = select_timestamps(timestamps).unwrap();
+
+        assert_eq!(date, "2022-07-04T00:00:00Z");
+        assert_eq!(updated, None);
+    }
 }